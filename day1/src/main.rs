@@ -1,58 +1,866 @@
-use std::fs::File;
+use std::collections::VecDeque;
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
 use std::io::{self, prelude::*, BufReader};
+use std::str::FromStr;
+use std::thread;
+use std::time::Instant;
 
-fn to_digit(s: &str) -> u32 {
-    match s {
-        "one" => 1,
-        "two" => 2,
-        "three" => 3,
-        "four" => 4,
-        "five" => 5,
-        "six" => 6,
-        "seven" => 7,
-        "eight" => 8,
-        "nine" => 9,
-        _ => s.parse::<u32>().unwrap(),
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CalibrationMode {
+    Digits,
+    DigitsAndWords,
+}
+
+#[derive(Debug)]
+struct ParseCalibrationModeError;
+
+impl FromStr for CalibrationMode {
+    type Err = ParseCalibrationModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "digits" => Ok(CalibrationMode::Digits),
+            "words" => Ok(CalibrationMode::DigitsAndWords),
+            _ => Err(ParseCalibrationModeError),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Grammar {
+    Standard,
+    Extended,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Vocabulary {
+    words: Vec<(String, u32)>,
+    extended_words: Vec<(String, u32)>,
+    ignore_case: bool,
+}
+
+#[derive(Debug)]
+struct ParseVocabularyError(String);
+
+impl Vocabulary {
+    fn new(words: &[&str]) -> Self {
+        Self {
+            words: words
+                .iter()
+                .zip(1..)
+                .map(|(word, value)| (word.to_string(), value))
+                .collect(),
+            extended_words: Vec::new(),
+            ignore_case: false,
+        }
+    }
+
+    fn english() -> Self {
+        let extended_words = [
+            ("zero", 0),
+            ("ten", 10),
+            ("eleven", 11),
+            ("twelve", 12),
+            ("thirteen", 13),
+            ("fourteen", 14),
+            ("fifteen", 15),
+            ("sixteen", 16),
+            ("seventeen", 17),
+            ("eighteen", 18),
+            ("nineteen", 19),
+            ("twenty", 20),
+            ("thirty", 30),
+            ("forty", 40),
+            ("fifty", 50),
+            ("sixty", 60),
+            ("seventy", 70),
+            ("eighty", 80),
+            ("ninety", 90),
+        ];
+
+        Self {
+            extended_words: extended_words
+                .iter()
+                .map(|(word, value)| (word.to_string(), *value))
+                .collect(),
+            ..Self::new(&[
+                "one", "two", "three", "four", "five", "six", "seven", "eight", "nine",
+            ])
+        }
+    }
+
+    fn german() -> Self {
+        Self::new(&[
+            "eins", "zwei", "drei", "vier", "fünf", "sechs", "sieben", "acht", "neun",
+        ])
+    }
+
+    fn french() -> Self {
+        Self::new(&[
+            "un", "deux", "trois", "quatre", "cinq", "six", "sept", "huit", "neuf",
+        ])
+    }
+
+    fn spanish() -> Self {
+        Self::new(&[
+            "uno", "dos", "tres", "cuatro", "cinco", "seis", "siete", "ocho", "nueve",
+        ])
+    }
+
+    fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "english" | "en" => Some(Self::english()),
+            "german" | "de" => Some(Self::german()),
+            "french" | "fr" => Some(Self::french()),
+            "spanish" | "es" => Some(Self::spanish()),
+            _ => None,
+        }
+    }
+
+    fn load(path: &str) -> io::Result<Self> {
+        fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?
+            .parse::<Vocabulary>()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, e.0)))
+    }
+
+    fn ignore_case(self, ignore_case: bool) -> Self {
+        Self {
+            ignore_case,
+            ..self
+        }
+    }
+}
+
+impl FromStr for Vocabulary {
+    type Err = ParseVocabularyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let entries = s
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let (word, value) = line.split_once('=').ok_or(ParseVocabularyError(format!(
+                    "expected `word = number`, got `{}`",
+                    line
+                )))?;
+                let (word, value) = (word.trim(), value.trim());
+                match value.parse::<u32>() {
+                    Ok(value) if !word.is_empty() && value < 100 => Ok((word.to_string(), value)),
+                    _ => Err(ParseVocabularyError(format!("invalid entry `{}`", line))),
+                }
+            })
+            .collect::<Result<Vec<(String, u32)>, ParseVocabularyError>>()?;
+
+        let (words, extended_words) = entries
+            .into_iter()
+            .partition(|(_, value)| (1..=9).contains(value));
+
+        Ok(Self {
+            words,
+            extended_words,
+            ignore_case: false,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Match {
+    start: usize,
+    end: usize,
+    value: u32,
+}
+
+impl Match {
+    fn first_digit(&self) -> u32 {
+        let mut value = self.value;
+        while value >= 10 {
+            value /= 10;
+        }
+        value
+    }
+
+    fn last_digit(&self) -> u32 {
+        self.value % 10
+    }
+}
+
+fn calibration_value(first: &Match, last: &Match) -> u32 {
+    10 * first.first_digit() + last.last_digit()
+}
+
+#[derive(Debug)]
+struct Scanner {
+    transitions: Vec<[usize; 256]>,
+    outputs: Vec<Vec<(usize, u32)>>,
+    ignore_case: bool,
+}
+
+fn case_variants(pattern: &str) -> Vec<String> {
+    pattern.chars().fold(vec![String::new()], |acc, ch| {
+        let mut alternatives = vec![ch.to_lowercase().collect::<String>()];
+        if !ch.is_ascii() {
+            let upper = ch.to_uppercase().collect::<String>();
+            if upper != alternatives[0] {
+                alternatives.push(upper);
+            }
+        }
+        acc.iter()
+            .flat_map(|prefix| {
+                alternatives
+                    .iter()
+                    .map(move |alt| format!("{}{}", prefix, alt))
+            })
+            .collect()
+    })
+}
+
+impl Scanner {
+    fn new(patterns: &[(String, u32)], ignore_case: bool) -> Self {
+        let mut transitions = vec![[0; 256]];
+        let mut children = vec![[None; 256]];
+        let mut outputs = vec![Vec::new()];
+
+        let patterns = patterns
+            .iter()
+            .flat_map(|(pattern, value)| {
+                let variants = if ignore_case {
+                    case_variants(pattern)
+                } else {
+                    vec![pattern.clone()]
+                };
+                variants.into_iter().map(move |variant| (variant, *value))
+            })
+            .collect::<Vec<(String, u32)>>();
+
+        for (pattern, value) in &patterns {
+            let mut state = 0;
+            for &b in pattern.as_bytes() {
+                state = match children[state][b as usize] {
+                    Some(next) => next,
+                    None => {
+                        transitions.push([0; 256]);
+                        children.push([None; 256]);
+                        outputs.push(Vec::new());
+                        let next = transitions.len() - 1;
+                        children[state][b as usize] = Some(next);
+                        next
+                    }
+                };
+            }
+            outputs[state].push((pattern.len(), *value));
+        }
+
+        let mut fail = vec![0; transitions.len()];
+        let mut queue = VecDeque::from([0]);
+
+        while let Some(state) = queue.pop_front() {
+            for b in 0..256 {
+                match children[state][b] {
+                    Some(next) => {
+                        let link = if state == 0 {
+                            0
+                        } else {
+                            transitions[fail[state]][b]
+                        };
+                        fail[next] = link;
+                        let inherited = outputs[link].clone();
+                        outputs[next].extend(inherited);
+                        transitions[state][b] = next;
+                        queue.push_back(next);
+                    }
+                    None => transitions[state][b] = transitions[fail[state]][b],
+                }
+            }
+        }
+
+        Self {
+            transitions,
+            outputs,
+            ignore_case,
+        }
+    }
+
+    fn from_mode(mode: CalibrationMode, grammar: Grammar, vocabulary: &Vocabulary) -> Self {
+        let words = match (mode, grammar) {
+            (CalibrationMode::Digits, _) => vec![],
+            (CalibrationMode::DigitsAndWords, Grammar::Standard) => vocabulary.words.clone(),
+            (CalibrationMode::DigitsAndWords, Grammar::Extended) => vocabulary
+                .words
+                .iter()
+                .chain(vocabulary.extended_words.iter())
+                .cloned()
+                .collect(),
+        };
+
+        let digits = match grammar {
+            Grammar::Standard => 1..=9,
+            Grammar::Extended => 0..=9,
+        };
+
+        let patterns = words
+            .into_iter()
+            .chain(digits.map(|d| (d.to_string(), d)))
+            .collect::<Vec<(String, u32)>>();
+
+        Self::new(&patterns, vocabulary.ignore_case)
+    }
+
+    fn scan(&self, line: &[u8]) -> Option<(Match, Match)> {
+        let mut state = 0;
+        let mut first: Option<Match> = None;
+        let mut last: Option<Match> = None;
+
+        for (i, &b) in line.iter().enumerate() {
+            let b = if self.ignore_case {
+                b.to_ascii_lowercase()
+            } else {
+                b
+            };
+            state = self.transitions[state][b as usize];
+            for &(len, value) in &self.outputs[state] {
+                let m = Match {
+                    start: i + 1 - len,
+                    end: i + 1,
+                    value,
+                };
+                if first.is_none_or(|f| m.start < f.start || (m.start == f.start && m.end > f.end))
+                {
+                    first = Some(m);
+                }
+                if last.is_none_or(|l| m.end > l.end) {
+                    last = Some(m);
+                }
+            }
+        }
+
+        first.zip(last)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum CalibrationErrorReason {
+    Unreadable(String),
+    NoDigit,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct CalibrationError {
+    line: usize,
+    reason: CalibrationErrorReason,
+}
+
+impl fmt::Display for CalibrationErrorReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CalibrationErrorReason::Unreadable(e) => write!(f, "unreadable ({})", e),
+            CalibrationErrorReason::NoDigit => write!(f, "no digit found"),
+        }
+    }
+}
+
+impl fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+fn parse_line(
+    row: usize,
+    line: &str,
+    scanner: &Scanner,
+) -> Result<(Match, Match), CalibrationError> {
+    scanner.scan(line.as_bytes()).ok_or(CalibrationError {
+        line: row,
+        reason: CalibrationErrorReason::NoDigit,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ExplainFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug)]
+struct ParseExplainFormatError;
+
+impl FromStr for ExplainFormat {
+    type Err = ParseExplainFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(ExplainFormat::Text),
+            "json" => Ok(ExplainFormat::Json),
+            _ => Err(ParseExplainFormatError),
+        }
+    }
+}
+
+fn json_escape(s: &str) -> String {
+    s.chars().fold(String::new(), |mut acc, ch| {
+        match ch {
+            '"' => acc.push_str("\\\""),
+            '\\' => acc.push_str("\\\\"),
+            ch if (ch as u32) < 0x20 => acc.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => acc.push(ch),
+        }
+        acc
+    })
+}
+
+fn explain(
+    row: usize,
+    mode: CalibrationMode,
+    line: &str,
+    result: &Result<(Match, Match), CalibrationError>,
+    format: ExplainFormat,
+) -> String {
+    match (result, format) {
+        (Ok((first, last)), ExplainFormat::Text) => format!(
+            "line {} [{:?}]: first `{}` at {}..{}, last `{}` at {}..{} -> {}",
+            row,
+            mode,
+            &line[first.start..first.end],
+            first.start,
+            first.end,
+            &line[last.start..last.end],
+            last.start,
+            last.end,
+            calibration_value(first, last)
+        ),
+        (Ok((first, last)), ExplainFormat::Json) => {
+            let token = |m: &Match, digit: u32| {
+                format!(
+                    "{{\"start\":{},\"end\":{},\"text\":\"{}\",\"number\":{},\"digit\":{}}}",
+                    m.start,
+                    m.end,
+                    json_escape(&line[m.start..m.end]),
+                    m.value,
+                    digit
+                )
+            };
+            format!(
+                "{{\"line\":{},\"mode\":\"{:?}\",\"first\":{},\"last\":{},\"value\":{}}}",
+                row,
+                mode,
+                token(first, first.first_digit()),
+                token(last, last.last_digit()),
+                calibration_value(first, last)
+            )
+        }
+        (Err(e), ExplainFormat::Text) => format!("line {} [{:?}]: {}", row, mode, e.reason),
+        (Err(e), ExplainFormat::Json) => format!(
+            "{{\"line\":{},\"mode\":\"{:?}\",\"error\":\"{}\"}}",
+            row,
+            mode,
+            json_escape(&e.reason.to_string())
+        ),
+    }
+}
+
+#[derive(Debug, Default)]
+struct Summary {
+    sum: u64,
+    lines: usize,
+    errors: Vec<CalibrationError>,
+}
+
+impl Summary {
+    fn record(&mut self, result: Result<(Match, Match), CalibrationError>) {
+        self.lines += 1;
+        match result {
+            Ok((first, last)) => self.sum += calibration_value(&first, &last) as u64,
+            Err(e) => self.errors.push(e),
+        }
+    }
+
+    fn merge(&mut self, other: Summary, line_offset: usize) {
+        self.sum += other.sum;
+        self.lines += other.lines;
+        self.errors
+            .extend(other.errors.into_iter().map(|e| CalibrationError {
+                line: e.line + line_offset,
+                ..e
+            }));
+    }
+}
+
+fn summary_json(mode: CalibrationMode, summary: &Summary) -> String {
+    let errors = summary
+        .errors
+        .iter()
+        .map(|e| {
+            format!(
+                "{{\"line\":{},\"error\":\"{}\"}}",
+                e.line,
+                json_escape(&e.reason.to_string())
+            )
+        })
+        .collect::<Vec<String>>();
+    format!(
+        "{{\"mode\":\"{:?}\",\"sum\":{},\"lines\":{},\"skipped\":[{}]}}",
+        mode,
+        summary.sum,
+        summary.lines,
+        errors.join(",")
+    )
+}
+
+#[derive(Debug)]
+struct Options {
+    modes: Vec<CalibrationMode>,
+    vocabulary: Vocabulary,
+    grammar: Grammar,
+    strict: bool,
+    explain: Option<ExplainFormat>,
+    parallel: bool,
+    threads: usize,
+}
+
+impl Options {
+    fn from_args() -> io::Result<Self> {
+        let mut modes = vec![CalibrationMode::Digits, CalibrationMode::DigitsAndWords];
+        let mut vocabulary = Vocabulary::english();
+        let mut ignore_case = false;
+        let mut grammar = Grammar::Standard;
+        let mut strict = false;
+        let mut explain = None;
+        let mut parallel = false;
+        let mut threads = thread::available_parallelism().map_or(1, |n| n.get());
+
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match &arg[..] {
+                "--mode" => {
+                    let mode = args.next().ok_or(invalid_input("--mode needs a value"))?;
+                    modes = match &mode[..] {
+                        "both" => vec![CalibrationMode::Digits, CalibrationMode::DigitsAndWords],
+                        _ => vec![mode.parse::<CalibrationMode>().map_err(|_| {
+                            invalid_input(&format!("unknown calibration mode `{}`", mode))
+                        })?],
+                    };
+                }
+                "--vocabulary" => {
+                    let name = args
+                        .next()
+                        .ok_or(invalid_input("--vocabulary needs a value"))?;
+                    vocabulary = match Vocabulary::builtin(&name) {
+                        Some(vocabulary) => vocabulary,
+                        None => Vocabulary::load(&name)?,
+                    };
+                }
+                "--ignore-case" => ignore_case = true,
+                "--extended" => grammar = Grammar::Extended,
+                "--strict" => strict = true,
+                "--parallel" => parallel = true,
+                "--threads" => {
+                    let n = args
+                        .next()
+                        .ok_or(invalid_input("--threads needs a value"))?;
+                    threads = match n.parse::<usize>() {
+                        Ok(n) if n > 0 => n,
+                        _ => return Err(invalid_input(&format!("invalid thread count `{}`", n))),
+                    };
+                }
+                "--explain" => {
+                    let format = args
+                        .next()
+                        .ok_or(invalid_input("--explain needs a value"))?;
+                    explain = Some(format.parse::<ExplainFormat>().map_err(|_| {
+                        invalid_input(&format!("unknown explain format `{}`", format))
+                    })?);
+                }
+                _ => return Err(invalid_input(&format!("unknown argument `{}`", arg))),
+            }
+        }
+
+        if parallel && explain.is_some() {
+            return Err(invalid_input(
+                "--explain cannot be combined with --parallel",
+            ));
+        }
+
+        Ok(Self {
+            modes,
+            vocabulary: vocabulary.ignore_case(ignore_case),
+            grammar,
+            strict,
+            explain,
+            parallel,
+            threads,
+        })
     }
 }
 
-fn parse_line(line: String) -> (u32, u32) {
-    let words = vec![
-        "one", "two", "three", "four", "five", "six", "seven", "eight", "nine",
-    ];
+fn invalid_input(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+fn calibrate(
+    reader: impl BufRead,
+    scanners: &[Scanner],
+    modes: &[CalibrationMode],
+    explain_format: Option<ExplainFormat>,
+) -> io::Result<Vec<Summary>> {
+    let mut summaries = modes.iter().map(|_| Summary::default()).collect::<Vec<_>>();
+
+    for (i, line) in reader.lines().enumerate() {
+        let row = i + 1;
+        match line {
+            Ok(line) => {
+                for ((summary, scanner), mode) in summaries.iter_mut().zip(scanners).zip(modes) {
+                    let mode = *mode;
+                    let result = parse_line(row, &line, scanner);
+                    if let Some(format) = explain_format {
+                        println!("{}", explain(row, mode, &line, &result, format));
+                    }
+                    summary.record(result);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                for (summary, &mode) in summaries.iter_mut().zip(modes) {
+                    let result = Err(CalibrationError {
+                        line: row,
+                        reason: CalibrationErrorReason::Unreadable(e.to_string()),
+                    });
+                    if let Some(format) = explain_format {
+                        println!("{}", explain(row, mode, "", &result, format));
+                    }
+                    summary.record(result);
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
 
-    let digits = vec!["1", "2", "3", "4", "5", "6", "7", "8", "9"];
+    Ok(summaries)
+}
 
-    let (_, p) = words
+fn calibrate_chunk(chunk: &[u8], scanners: &[Scanner]) -> Vec<Summary> {
+    let mut summaries = scanners
         .iter()
-        .chain(digits.iter())
-        .map(|p| (line.find(p), p.to_string()))
-        .filter_map(|(i, p)| if let Some(i) = i { Some((i, p)) } else { None })
-        .min_by(|(i, _), (j, _)| i.cmp(j))
-        .unwrap();
+        .map(|_| Summary::default())
+        .collect::<Vec<_>>();
+
+    if chunk.is_empty() {
+        return summaries;
+    }
+
+    let chunk = chunk.strip_suffix(b"\n").unwrap_or(chunk);
+
+    for (i, line) in chunk.split(|&b| b == b'\n').enumerate() {
+        let row = i + 1;
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        for (summary, scanner) in summaries.iter_mut().zip(scanners) {
+            let result = match std::str::from_utf8(line) {
+                Ok(_) => scanner.scan(line).ok_or(CalibrationErrorReason::NoDigit),
+                Err(_) => Err(CalibrationErrorReason::Unreadable(
+                    "stream did not contain valid UTF-8".to_string(),
+                )),
+            };
+            summary.record(result.map_err(|reason| CalibrationError { line: row, reason }));
+        }
+    }
+
+    summaries
+}
 
-    let (_, q) = words
+fn split_chunks(data: &[u8], n: usize) -> Vec<&[u8]> {
+    let mut chunks = Vec::with_capacity(n);
+    let mut rest = data;
+
+    for i in (1..=n).rev() {
+        let target = rest.len() / i;
+        let cut = match rest[target..].iter().position(|&b| b == b'\n') {
+            Some(p) if i > 1 => target + p + 1,
+            _ => rest.len(),
+        };
+        let (chunk, tail) = rest.split_at(cut);
+        chunks.push(chunk);
+        rest = tail;
+    }
+
+    chunks
+}
+
+const BLOCK_SIZE: usize = 64 * 1024 * 1024;
+
+fn calibrate_parallel(
+    mut reader: impl Read,
+    scanners: &[Scanner],
+    threads: usize,
+) -> io::Result<(Vec<Summary>, usize)> {
+    let mut summaries = scanners
         .iter()
-        .chain(digits.iter())
-        .map(|p| (line.rfind(p), p.to_string()))
-        .filter_map(|(i, p)| if let Some(i) = i { Some((i, p)) } else { None })
-        .max_by(|(i, p), (j, q)| (i + &p.len()).cmp(&(j + q.len())))
-        .unwrap();
+        .map(|_| Summary::default())
+        .collect::<Vec<_>>();
+    let mut carry = Vec::new();
+    let mut bytes = 0;
+
+    loop {
+        let mut block = std::mem::take(&mut carry);
+        let n = (&mut reader)
+            .take(BLOCK_SIZE as u64)
+            .read_to_end(&mut block)?;
+        let eof = n < BLOCK_SIZE;
+
+        let cut = match block.iter().rposition(|&b| b == b'\n') {
+            _ if eof => block.len(),
+            Some(p) => p + 1,
+            None => {
+                carry = block;
+                continue;
+            }
+        };
+        carry = block.split_off(cut);
+        bytes += block.len();
+
+        let results = thread::scope(|s| {
+            split_chunks(&block, threads)
+                .into_iter()
+                .map(|chunk| s.spawn(move || calibrate_chunk(chunk, scanners)))
+                .collect::<Vec<_>>()
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<Vec<Vec<Summary>>>()
+        });
 
-    (to_digit(&p[..]), to_digit(&q[..]))
+        for result in results {
+            let line_offset = summaries[0].lines;
+            for (summary, other) in summaries.iter_mut().zip(result) {
+                summary.merge(other, line_offset);
+            }
+        }
+
+        if eof {
+            break;
+        }
+    }
+
+    Ok((summaries, bytes))
 }
 
 fn main() -> io::Result<()> {
+    let options = Options::from_args()?;
+
     let file = File::open("input.txt")?;
-    let reader = BufReader::new(file);
 
-    let sum = reader.lines().fold(0, |acc, line| {
-        let (first, last) = parse_line(line.unwrap_or("".to_string()));
-        acc + 10 * first + last
-    });
+    let modes = &options.modes;
+
+    let scanners = modes
+        .iter()
+        .map(|&mode| Scanner::from_mode(mode, options.grammar, &options.vocabulary))
+        .collect::<Vec<Scanner>>();
+
+    let summaries = if options.parallel {
+        let start = Instant::now();
+        let (summaries, bytes) = calibrate_parallel(file, &scanners, options.threads)?;
+        let elapsed = start.elapsed().as_secs_f64();
+        println!(
+            "Throughput: {:.0} bytes/sec ({} bytes in {:.3}s on {} threads)",
+            bytes as f64 / elapsed,
+            bytes,
+            elapsed,
+            options.threads
+        );
+        summaries
+    } else {
+        calibrate(BufReader::new(file), &scanners, modes, options.explain)?
+    };
+
+    for (summary, &mode) in summaries.iter().zip(modes) {
+        if options.explain == Some(ExplainFormat::Json) {
+            println!("{}", summary_json(mode, summary));
+            continue;
+        }
+        println!("{:?}: {}", mode, summary.sum);
+        if !summary.errors.is_empty() {
+            println!(
+                "  skipped {} of {} lines",
+                summary.errors.len(),
+                summary.lines
+            );
+            for e in &summary.errors {
+                println!("  {}", e);
+            }
+        }
+    }
 
-    println!("{}", sum);
+    if options.strict {
+        let failure = summaries
+            .iter()
+            .zip(modes)
+            .find_map(|(summary, mode)| summary.errors.first().map(|e| (mode, e)));
+        if let Some((mode, e)) = failure {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("strict mode ({:?}): {}", mode, e),
+            ));
+        }
+    }
 
     Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(line: &str, mode: CalibrationMode) -> Option<u32> {
+        let scanner = Scanner::from_mode(mode, Grammar::Standard, &Vocabulary::english());
+        scanner
+            .scan(line.as_bytes())
+            .map(|(first, last)| calibration_value(&first, &last))
+    }
+
+    #[test]
+    fn overlapping_words() {
+        assert_eq!(value("eightwo", CalibrationMode::DigitsAndWords), Some(82));
+        assert_eq!(value("oneight", CalibrationMode::DigitsAndWords), Some(18));
+        assert_eq!(value("twone", CalibrationMode::DigitsAndWords), Some(21));
+        assert_eq!(
+            value("xtwone3four", CalibrationMode::DigitsAndWords),
+            Some(24)
+        );
+        assert_eq!(
+            value("eightwothree", CalibrationMode::DigitsAndWords),
+            Some(83)
+        );
+        assert_eq!(value("eightwothree", CalibrationMode::Digits), None);
+        assert_eq!(value("a1b2c3d4e5f", CalibrationMode::Digits), Some(15));
+    }
+    #[test]
+    fn parallel_matches_sequential() {
+        let mut data = Vec::new();
+        for i in 0..500 {
+            match i % 7 {
+                0 => data.extend_from_slice(b"no digits here\n"),
+                1 => data.extend_from_slice(b"bad \xff byte 4\n"),
+                2 => data.extend_from_slice(b"eightwothree\r\n"),
+                _ => data.extend_from_slice(format!("x{}twone{}y\n", i % 10, i % 9).as_bytes()),
+            }
+        }
+        data.extend_from_slice(b"7pqrstsixteen");
+
+        let modes = [CalibrationMode::Digits, CalibrationMode::DigitsAndWords];
+        let scanners = modes
+            .iter()
+            .map(|&mode| Scanner::from_mode(mode, Grammar::Standard, &Vocabulary::english()))
+            .collect::<Vec<Scanner>>();
+
+        let sequential = calibrate(&data[..], &scanners, &modes, None).unwrap();
+        for threads in [1, 2, 3, 8] {
+            let (parallel, bytes) = calibrate_parallel(&data[..], &scanners, threads).unwrap();
+            assert_eq!(bytes, data.len());
+            for (p, s) in parallel.iter().zip(&sequential) {
+                assert_eq!(p.sum, s.sum);
+                assert_eq!(p.lines, s.lines);
+                assert_eq!(p.errors, s.errors);
+            }
+        }
+    }
+}
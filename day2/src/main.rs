@@ -1,88 +1,846 @@
-use std::fs::File;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::env;
+use std::fs::{self, File};
 use std::io::{self, prelude::*, BufReader};
+use std::str::FromStr;
 
-#[derive(Debug)]
-struct Cubes {
-    red: u32,
-    green: u32,
-    blue: u32,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Color(usize);
+
+#[derive(Debug, Default)]
+struct Palette {
+    names: Vec<String>,
+    colors: HashMap<String, Color>,
 }
 
-impl Default for Cubes {
-    fn default() -> Self {
-        Self {
-            red: 0,
-            green: 0,
-            blue: 0,
+impl Palette {
+    fn intern(&mut self, name: &str) -> Color {
+        if let Some(color) = self.colors.get(name) {
+            return *color;
         }
+        let color = Color(self.names.len());
+        self.names.push(name.to_string());
+        self.colors.insert(name.to_string(), color);
+        color
+    }
+
+    fn name(&self, color: Color) -> &str {
+        &self.names[color.0]
     }
 }
 
+#[derive(Debug, Default, Clone)]
+struct Cubes {
+    counts: BTreeMap<Color, u32>,
+}
+
 impl Cubes {
-    fn new(red: u32, green: u32, blue: u32) -> Self {
-        Self { red, green, blue }
+    fn get(&self, color: Color) -> u32 {
+        self.counts.get(&color).copied().unwrap_or(0)
+    }
+
+    fn add(mut self, color: Color, count: u32) -> Self {
+        *self.counts.entry(color).or_insert(0) += count;
+        self
+    }
+
+    fn max(mut self, other: &Cubes) -> Self {
+        for (color, count) in &other.counts {
+            let entry = self.counts.entry(*color).or_insert(0);
+            *entry = (*entry).max(*count);
+        }
+        self
+    }
+
+    fn colors(&self) -> impl Iterator<Item = Color> + '_ {
+        self.counts.keys().copied()
+    }
+
+    fn power(&self, colors: &BTreeSet<Color>) -> Option<u64> {
+        colors
+            .iter()
+            .try_fold(1u64, |acc, color| acc.checked_mul(self.get(*color) as u64))
+    }
+}
+
+impl PartialEq for Cubes {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
     }
 }
 
-fn required_cubes(game: &Vec<Cubes>) -> Cubes {
-    Cubes {
-        red: game
+impl PartialOrd for Cubes {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        let colors = self
+            .colors()
+            .chain(other.colors())
+            .collect::<BTreeSet<Color>>();
+        let le = colors
             .iter()
-            .max_by(|lhs, rhs| lhs.red.cmp(&rhs.red))
-            .map(|color| color.red)
-            .unwrap(),
-        green: game
+            .all(|color| self.get(*color) <= other.get(*color));
+        let ge = colors
             .iter()
-            .max_by(|lhs, rhs| lhs.green.cmp(&rhs.green))
-            .map(|color| color.green)
-            .unwrap(),
-        blue: game
+            .all(|color| self.get(*color) >= other.get(*color));
+        match (le, ge) {
+            (true, true) => Some(Ordering::Equal),
+            (true, false) => Some(Ordering::Less),
+            (false, true) => Some(Ordering::Greater),
+            (false, false) => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Game {
+    id: u32,
+    turns: Vec<Cubes>,
+}
+
+fn required_cubes(game: &[Cubes]) -> Cubes {
+    game.iter()
+        .fold(Cubes::default(), |acc, turn| acc.max(turn))
+}
+
+fn is_feasible(game: &Game, bag: &Cubes) -> bool {
+    required_cubes(&game.turns) <= *bag
+}
+
+fn observed_colors(games: &[Game]) -> BTreeSet<Color> {
+    games
+        .iter()
+        .flat_map(|game| game.turns.iter().flat_map(|turn| turn.colors()))
+        .collect()
+}
+
+fn feasible_sum(games: &[Game], bag: &Cubes) -> u32 {
+    games
+        .iter()
+        .filter(|game| is_feasible(game, bag))
+        .fold(0, |acc, game| acc + game.id)
+}
+
+fn power_overflow(game: &Game) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("power overflow in game {}", game.id),
+    )
+}
+
+fn power_sum(games: &[Game], colors: &BTreeSet<Color>) -> io::Result<u64> {
+    games.iter().try_fold(0u64, |acc, game| {
+        required_cubes(&game.turns)
+            .power(colors)
+            .and_then(|power| acc.checked_add(power))
+            .ok_or(power_overflow(game))
+    })
+}
+
+#[derive(Debug)]
+struct GameStats {
+    id: u32,
+    turns: usize,
+    required: Cubes,
+    drawn: u32,
+    power: u64,
+    feasible: bool,
+}
+
+fn game_stats(game: &Game, colors: &BTreeSet<Color>, bag: &Cubes) -> io::Result<GameStats> {
+    let required = required_cubes(&game.turns);
+    Ok(GameStats {
+        id: game.id,
+        turns: game.turns.len(),
+        drawn: game
+            .turns
             .iter()
-            .max_by(|lhs, rhs| lhs.blue.cmp(&rhs.blue))
-            .map(|color| color.blue)
-            .unwrap(),
+            .flat_map(|turn| turn.counts.values())
+            .sum(),
+        power: required.power(colors).ok_or(power_overflow(game))?,
+        feasible: is_feasible(game, bag),
+        required,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReportFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Debug)]
+struct ParseReportFormatError;
+
+impl FromStr for ReportFormat {
+    type Err = ParseReportFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(ReportFormat::Csv),
+            "json" => Ok(ReportFormat::Json),
+            _ => Err(ParseReportFormatError),
+        }
+    }
+}
+
+fn csv_escape(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn json_escape(s: &str) -> String {
+    s.chars().fold(String::new(), |mut acc, ch| {
+        match ch {
+            '"' => acc.push_str("\\\""),
+            '\\' => acc.push_str("\\\\"),
+            ch if (ch as u32) < 0x20 => acc.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => acc.push(ch),
+        }
+        acc
+    })
+}
+
+fn write_report(
+    out: &mut impl Write,
+    stats: &[GameStats],
+    colors: &BTreeSet<Color>,
+    palette: &Palette,
+    format: ReportFormat,
+) -> io::Result<()> {
+    match format {
+        ReportFormat::Csv => {
+            let header = ["id".to_string(), "turns".to_string()]
+                .into_iter()
+                .chain(
+                    colors
+                        .iter()
+                        .map(|color| format!("max_{}", palette.name(*color))),
+                )
+                .chain([
+                    "total".to_string(),
+                    "power".to_string(),
+                    "feasible".to_string(),
+                ])
+                .map(|column| csv_escape(&column))
+                .collect::<Vec<String>>();
+            writeln!(out, "{}", header.join(","))?;
+            for game in stats {
+                let row = [game.id.to_string(), game.turns.to_string()]
+                    .into_iter()
+                    .chain(
+                        colors
+                            .iter()
+                            .map(|color| game.required.get(*color).to_string()),
+                    )
+                    .chain([
+                        game.drawn.to_string(),
+                        game.power.to_string(),
+                        game.feasible.to_string(),
+                    ])
+                    .collect::<Vec<String>>();
+                writeln!(out, "{}", row.join(","))?;
+            }
+        }
+        ReportFormat::Json => {
+            writeln!(out, "[")?;
+            for (i, game) in stats.iter().enumerate() {
+                let max = colors
+                    .iter()
+                    .map(|color| {
+                        format!(
+                            "\"{}\":{}",
+                            json_escape(palette.name(*color)),
+                            game.required.get(*color)
+                        )
+                    })
+                    .collect::<Vec<String>>();
+                writeln!(
+                    out,
+                    "  {{\"id\":{},\"turns\":{},\"max\":{{{}}},\"total\":{},\"power\":{},\"feasible\":{}}}{}",
+                    game.id,
+                    game.turns,
+                    max.join(","),
+                    game.drawn,
+                    game.power,
+                    game.feasible,
+                    if i + 1 < stats.len() { "," } else { "" }
+                )?;
+            }
+            writeln!(out, "]")?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        ((self.next_u64() as u128 * n as u128) >> 64) as usize
     }
+
+    fn shuffle<T>(&mut self, items: &mut [T], count: usize) {
+        for i in 0..count.min(items.len()) {
+            let j = i + self.below(items.len() - i);
+            items.swap(i, j);
+        }
+    }
+}
+
+fn simulate(bag: &Cubes, games: u32, turns: usize, rng: &mut Rng) -> Vec<Game> {
+    let mut pool = bag
+        .counts
+        .iter()
+        .flat_map(|(color, count)| std::iter::repeat_n(*color, *count as usize))
+        .collect::<Vec<Color>>();
+
+    (1..=games)
+        .map(|id| Game {
+            id,
+            turns: (0..turns)
+                .map(|_| {
+                    let drawn = 1 + rng.below(pool.len());
+                    rng.shuffle(&mut pool, drawn);
+                    pool[..drawn]
+                        .iter()
+                        .fold(Cubes::default(), |acc, color| acc.add(*color, 1))
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+fn format_game(game: &Game, palette: &Palette, rng: &mut Rng) -> String {
+    let turns = game
+        .turns
+        .iter()
+        .map(|turn| {
+            let mut cubes = turn
+                .counts
+                .iter()
+                .map(|(color, count)| format!("{} {}", count, palette.name(*color)))
+                .collect::<Vec<String>>();
+            let len = cubes.len();
+            rng.shuffle(&mut cubes, len);
+            cubes.join(", ")
+        })
+        .collect::<Vec<String>>();
+    format!("Game {}: {}", game.id, turns.join("; "))
+}
+
+fn ln_factorials(n: u32) -> Vec<f64> {
+    (1..=n).fold(vec![0.0], |mut acc, i| {
+        acc.push(acc[acc.len() - 1] + (i as f64).ln());
+        acc
+    })
 }
 
-fn parse_line(line: &str) -> Vec<Cubes> {
+fn ln_binomial(ln_fact: &[f64], n: u32, k: u32) -> f64 {
+    ln_fact[n as usize] - ln_fact[k as usize] - ln_fact[(n - k) as usize]
+}
+
+fn compositions(total: u32, parts: usize) -> Vec<Vec<u32>> {
+    match parts {
+        0 if total == 0 => vec![vec![]],
+        0 => vec![],
+        1 => vec![vec![total]],
+        _ => (0..=total)
+            .flat_map(|first| {
+                compositions(total - first, parts - 1)
+                    .into_iter()
+                    .map(move |mut rest| {
+                        rest.insert(0, first);
+                        rest
+                    })
+            })
+            .collect(),
+    }
+}
+
+fn feasible_bags(games: &[&Game], colors: &[Color], total: u32) -> Option<(Cubes, Vec<Cubes>)> {
+    let minimal = games.iter().fold(Cubes::default(), |acc, game| {
+        acc.max(&required_cubes(&game.turns))
+    });
+    let slack = total.checked_sub(minimal.counts.values().sum())?;
+
+    let bags = (0..=slack)
+        .flat_map(|extra| compositions(extra, colors.len()))
+        .map(|extra| {
+            colors
+                .iter()
+                .zip(extra)
+                .fold(minimal.clone(), |acc, (color, extra)| {
+                    acc.add(*color, extra)
+                })
+        })
+        .collect();
+
+    Some((minimal, bags))
+}
+
+#[derive(Debug)]
+struct Marginal {
+    color: Color,
+    mean: f64,
+    low: u32,
+    high: u32,
+}
+
+#[derive(Debug)]
+struct Estimate {
+    mle: Cubes,
+    probability: f64,
+    candidates: usize,
+    marginals: Vec<Marginal>,
+}
+
+fn estimate_bag(game: &[Cubes], colors: &[Color], size: u32, level: f64) -> Option<Estimate> {
+    let required = required_cubes(game);
+    let minimum = colors
+        .iter()
+        .map(|color| required.get(*color))
+        .collect::<Vec<u32>>();
+    let slack = size.checked_sub(minimum.iter().sum())?;
+
+    let ln_fact = ln_factorials(size);
+
+    let candidates = compositions(slack, colors.len())
+        .into_iter()
+        .map(|extra| {
+            let counts = extra
+                .iter()
+                .zip(&minimum)
+                .map(|(extra, minimum)| extra + minimum)
+                .collect::<Vec<u32>>();
+            let ln_likelihood = game
+                .iter()
+                .map(|turn| {
+                    let drawn = colors.iter().map(|color| turn.get(*color)).sum::<u32>();
+                    colors
+                        .iter()
+                        .zip(&counts)
+                        .map(|(color, count)| ln_binomial(&ln_fact, *count, turn.get(*color)))
+                        .sum::<f64>()
+                        - ln_binomial(&ln_fact, size, drawn)
+                })
+                .sum::<f64>();
+            (counts, ln_likelihood)
+        })
+        .collect::<Vec<(Vec<u32>, f64)>>();
+
+    let max = candidates
+        .iter()
+        .map(|(_, ln_likelihood)| *ln_likelihood)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let norm = candidates
+        .iter()
+        .map(|(_, ln_likelihood)| (ln_likelihood - max).exp())
+        .sum::<f64>();
+    let posterior = candidates
+        .iter()
+        .map(|(counts, ln_likelihood)| (counts, (ln_likelihood - max).exp() / norm))
+        .collect::<Vec<(&Vec<u32>, f64)>>();
+
+    let (mle, probability) = posterior
+        .iter()
+        .max_by(|(_, p), (_, q)| p.total_cmp(q))
+        .map(|(counts, p)| (*counts, *p))?;
+
+    let tail = (1.0 - level) / 2.0;
+    let marginals = colors
+        .iter()
+        .enumerate()
+        .map(|(i, color)| {
+            let mut distribution = vec![0.0; size as usize + 1];
+            for (counts, p) in &posterior {
+                distribution[counts[i] as usize] += p;
+            }
+            let mean = distribution
+                .iter()
+                .enumerate()
+                .map(|(count, p)| count as f64 * p)
+                .sum::<f64>();
+            let quantile = |q: f64| {
+                let mut cumulative = 0.0;
+                distribution
+                    .iter()
+                    .position(|p| {
+                        cumulative += p;
+                        cumulative >= q
+                    })
+                    .unwrap_or(size as usize) as u32
+            };
+            Marginal {
+                color: *color,
+                mean,
+                low: quantile(tail),
+                high: quantile(1.0 - tail),
+            }
+        })
+        .collect();
+
+    Some(Estimate {
+        mle: colors
+            .iter()
+            .zip(mle)
+            .fold(Cubes::default(), |acc, (color, count)| {
+                acc.add(*color, *count)
+            }),
+        probability,
+        candidates: candidates.len(),
+        marginals,
+    })
+}
+
+fn parse_line(line: &str, palette: &mut Palette) -> Game {
     let start = line.find(':').unwrap();
-    parse_game(&line[start + 1..])
+    let id = line[..start]
+        .trim()
+        .strip_prefix("Game")
+        .unwrap()
+        .trim()
+        .parse::<u32>()
+        .unwrap();
+    Game {
+        id,
+        turns: parse_game(&line[start + 1..], palette),
+    }
 }
 
-fn parse_game(game: &str) -> Vec<Cubes> {
-    game.split(';').map(|turn| parse_turn(turn)).collect()
+fn parse_game(game: &str, palette: &mut Palette) -> Vec<Cubes> {
+    game.split(';')
+        .map(|turn| parse_turn(turn, palette))
+        .collect()
 }
 
-fn parse_turn(turn: &str) -> Cubes {
+fn parse_turn(turn: &str, palette: &mut Palette) -> Cubes {
     turn.split(',')
-        .fold(Cubes::default(), |acc, cube| parse_cube(acc, cube))
+        .fold(Cubes::default(), |acc, cube| parse_cube(acc, cube, palette))
 }
 
-fn parse_cube(cubes: Cubes, cube: &str) -> Cubes {
+fn parse_cube(cubes: Cubes, cube: &str, palette: &mut Palette) -> Cubes {
     let mut it = cube.trim().split(' ');
 
     let count = it.next().unwrap().parse::<u32>().unwrap();
 
     match it.next() {
-        Some("red") => Cubes::new(count, cubes.green, cubes.blue),
-        Some("green") => Cubes::new(cubes.red, count, cubes.blue),
-        Some("blue") => Cubes::new(cubes.red, cubes.green, count),
-        _ => cubes,
+        Some(name) => cubes.add(palette.intern(name), count),
+        None => cubes,
     }
 }
 
+fn parse_bag(bag: &str, palette: &mut Palette) -> io::Result<Cubes> {
+    bag.split(',').try_fold(Cubes::default(), |acc, cube| {
+        let mut it = cube.split_whitespace();
+        match (
+            it.next().map(|count| count.parse::<u32>()),
+            it.next(),
+            it.next(),
+        ) {
+            (Some(Ok(count)), Some(name), None) => Ok(acc.add(palette.intern(name), count)),
+            _ => Err(invalid_input(&format!(
+                "invalid bag entry `{}`, expected `count colour`",
+                cube.trim()
+            ))),
+        }
+    })
+}
+
+#[derive(Debug)]
+struct Simulation {
+    bag: String,
+    games: u32,
+    turns: usize,
+    seed: u64,
+    output: String,
+}
+
+#[derive(Debug)]
+struct Options {
+    input: String,
+    bag: String,
+    bag_size: Option<u32>,
+    solve: Option<u32>,
+    select: Option<Vec<u32>>,
+    report: Option<ReportFormat>,
+    report_output: Option<String>,
+    level: f64,
+    simulation: Option<Simulation>,
+}
+
+impl Options {
+    fn from_args() -> io::Result<Self> {
+        let mut options = Self {
+            input: "input.txt".to_string(),
+            bag: "12 red, 13 green, 14 blue".to_string(),
+            bag_size: None,
+            solve: None,
+            select: None,
+            report: None,
+            report_output: None,
+            level: 0.95,
+            simulation: None,
+        };
+        let mut simulation = Simulation {
+            bag: String::new(),
+            games: 100,
+            turns: 3,
+            seed: 0,
+            output: "simulated.txt".to_string(),
+        };
+        let mut simulate = false;
+
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or(invalid_input(&format!("{} needs a value", arg)))
+            };
+            match &arg[..] {
+                "--input" => options.input = value()?,
+                "--bag" => options.bag = value()?,
+                "--simulate" => {
+                    simulation.bag = value()?;
+                    simulate = true;
+                }
+                "--games" => simulation.games = parse_number(&value()?)?,
+                "--turns" => {
+                    let turns = value()?;
+                    simulation.turns = match parse_number(&turns)? {
+                        0 => return Err(invalid_input("--turns needs at least one turn")),
+                        turns => turns,
+                    };
+                }
+                "--seed" => simulation.seed = parse_number(&value()?)?,
+                "--output" => simulation.output = value()?,
+                "--report" => {
+                    let format = value()?;
+                    options.report = Some(format.parse::<ReportFormat>().map_err(|_| {
+                        invalid_input(&format!("unknown report format `{}`", format))
+                    })?);
+                }
+                "--report-output" => options.report_output = Some(value()?),
+                "--solve" => options.solve = Some(parse_number(&value()?)?),
+                "--select" => {
+                    options.select = Some(
+                        value()?
+                            .split(',')
+                            .map(|id| parse_number(id.trim()))
+                            .collect::<io::Result<Vec<u32>>>()?,
+                    )
+                }
+                "--estimate" => {
+                    let size = value()?;
+                    options.bag_size = Some(
+                        size.parse::<u32>()
+                            .map_err(|_| invalid_input(&format!("invalid bag size `{}`", size)))?,
+                    );
+                }
+                "--level" => {
+                    let level = value()?;
+                    options.level = match level.parse::<f64>() {
+                        Ok(level) if level > 0.0 && level < 1.0 => level,
+                        _ => return Err(invalid_input(&format!("invalid level `{}`", level))),
+                    };
+                }
+                _ => return Err(invalid_input(&format!("unknown argument `{}`", arg))),
+            }
+        }
+
+        if simulate {
+            options.simulation = Some(simulation);
+        }
+
+        Ok(options)
+    }
+}
+
+fn parse_number<T: FromStr>(s: &str) -> io::Result<T> {
+    s.parse::<T>()
+        .map_err(|_| invalid_input(&format!("invalid number `{}`", s)))
+}
+
+fn invalid_input(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
 fn main() -> io::Result<()> {
-    let file = File::open("input.txt")?;
+    let options = Options::from_args()?;
+
+    let mut palette = Palette::default();
+
+    let bag = parse_bag(&options.bag, &mut palette)?;
+
+    if let Some(simulation) = &options.simulation {
+        let source = parse_bag(&simulation.bag, &mut palette)?;
+        if source.counts.values().sum::<u32>() == 0 {
+            return Err(invalid_input("--simulate needs a non-empty bag"));
+        }
+
+        let mut rng = Rng(simulation.seed);
+        let games = simulate(&source, simulation.games, simulation.turns, &mut rng);
+
+        let mut output = File::create(&simulation.output)?;
+        for game in &games {
+            writeln!(output, "{}", format_game(game, &palette, &mut rng))?;
+        }
+
+        let answers = format!(
+            "Feasible: {}\nPower: {}\n",
+            feasible_sum(&games, &bag),
+            power_sum(&games, &observed_colors(&games))?
+        );
+        fs::write(format!("{}.answers", simulation.output), &answers)?;
+        print!("{}", answers);
+
+        return Ok(());
+    }
+
+    let file = File::open(&options.input)?;
     let reader = BufReader::new(file);
 
-    let sum = reader
-        .lines()
-        .filter_map(|line| line.ok())
-        .map(|line| parse_line(&line[..]))
-        .map(|game| required_cubes(&game))
-        .fold(0, |acc, cubes| acc + cubes.red * cubes.green * cubes.blue);
+    let mut games = Vec::new();
+    let mut unreadable = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        match line {
+            Ok(line) => games.push(parse_line(&line[..], &mut palette)),
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => unreadable.push(i + 1),
+            Err(e) => return Err(e),
+        }
+    }
+
+    let colors = observed_colors(&games);
 
-    println!("{}", sum);
+    let feasible = feasible_sum(&games, &bag);
+
+    let power = power_sum(&games, &colors)?;
+
+    println!(
+        "Colors: {}",
+        colors
+            .iter()
+            .map(|color| palette.name(*color))
+            .collect::<Vec<&str>>()
+            .join(", ")
+    );
+    println!("Feasible: {}", feasible);
+    println!("Power: {}", power);
+
+    if !unreadable.is_empty() {
+        println!(
+            "Skipped {} unreadable lines: {}",
+            unreadable.len(),
+            unreadable
+                .iter()
+                .map(|row| row.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+    }
+
+    if let Some(format) = options.report {
+        let path = options.report_output.clone().unwrap_or(match format {
+            ReportFormat::Csv => "report.csv".to_string(),
+            ReportFormat::Json => "report.json".to_string(),
+        });
+        let stats = games
+            .iter()
+            .map(|game| game_stats(game, &colors, &bag))
+            .collect::<io::Result<Vec<GameStats>>>()?;
+        let mut out = io::BufWriter::new(File::create(&path)?);
+        write_report(&mut out, &stats, &colors, &palette, format)?;
+        out.flush()?;
+        println!("Report: {}", path);
+    }
+
+    let describe = |cubes: &Cubes| {
+        colors
+            .iter()
+            .map(|color| format!("{} {}", cubes.get(*color), palette.name(*color)))
+            .collect::<Vec<String>>()
+            .join(", ")
+    };
+
+    if let Some(total) = options.solve {
+        if let Some(select) = &options.select {
+            let unknown = select
+                .iter()
+                .filter(|id| !games.iter().any(|game| game.id == **id))
+                .map(|id| id.to_string())
+                .collect::<Vec<String>>();
+            if !unknown.is_empty() {
+                return Err(invalid_input(&format!(
+                    "unknown game IDs in --select: {}",
+                    unknown.join(", ")
+                )));
+            }
+        }
+
+        let selected = games
+            .iter()
+            .filter(|game| {
+                options
+                    .select
+                    .as_ref()
+                    .is_none_or(|select| select.contains(&game.id))
+            })
+            .collect::<Vec<&Game>>();
+        let colors = colors.iter().copied().collect::<Vec<Color>>();
+        match feasible_bags(&selected, &colors, total) {
+            Some((minimal, bags)) => {
+                println!(
+                    "Minimal bag: {} (total {})",
+                    describe(&minimal),
+                    minimal.counts.values().sum::<u32>()
+                );
+                println!("Feasible bags up to {} cubes: {}", total, bags.len());
+                for bag in &bags {
+                    println!("  {}", describe(bag));
+                }
+            }
+            None => println!("No feasible bag with at most {} cubes", total),
+        }
+    }
+
+    if let Some(size) = options.bag_size {
+        let colors = colors.iter().copied().collect::<Vec<Color>>();
+        for game in &games {
+            match estimate_bag(&game.turns, &colors, size, options.level) {
+                Some(estimate) => {
+                    println!(
+                        "Game {}: {} (p = {:.4}, {} candidates)",
+                        game.id,
+                        describe(&estimate.mle),
+                        estimate.probability,
+                        estimate.candidates
+                    );
+                    for marginal in &estimate.marginals {
+                        println!(
+                            "  {}: mean {:.2}, {:.0}% interval [{}, {}]",
+                            palette.name(marginal.color),
+                            marginal.mean,
+                            options.level * 100.0,
+                            marginal.low,
+                            marginal.high
+                        );
+                    }
+                }
+                None => println!("Game {}: needs more than {} cubes", game.id, size),
+            }
+        }
+    }
 
     Ok(())
 }
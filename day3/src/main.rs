@@ -1,22 +1,194 @@
-use std::collections::HashMap;
-use std::fs::File;
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::fs::{self, File};
 use std::io::{self, prelude::*, BufReader};
+use std::str::FromStr;
 
 use itertools::Itertools;
 
 #[derive(Debug, PartialEq)]
 enum Token {
     Number((usize, usize), usize),
-    Gear(Option<usize>),
+    Symbol(char),
+    Gear(usize),
 }
 
-fn build_frame(pos: (usize, usize), width: usize) -> Vec<(usize, usize)> {
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Arity {
+    Exactly(usize),
+    AtLeast(usize),
+}
+
+impl Arity {
+    fn accepts(&self, n: usize) -> bool {
+        match self {
+            Arity::Exactly(k) => n == *k,
+            Arity::AtLeast(k) => n >= *k,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ParseArityError;
+
+impl FromStr for Arity {
+    type Err = ParseArityError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_suffix('+') {
+            Some(k) => k.parse().map(Arity::AtLeast).map_err(|_| ParseArityError),
+            None => s.parse().map(Arity::Exactly).map_err(|_| ParseArityError),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Aggregate {
+    Product,
+    Sum,
+    Max,
+}
+
+impl Aggregate {
+    fn apply(&self, vals: &[usize]) -> usize {
+        match self {
+            Aggregate::Product => vals.iter().product(),
+            Aggregate::Sum => vals.iter().sum(),
+            Aggregate::Max => vals.iter().copied().max().unwrap_or(0),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ParseAggregateError;
+
+impl FromStr for Aggregate {
+    type Err = ParseAggregateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "product" => Ok(Aggregate::Product),
+            "sum" => Ok(Aggregate::Sum),
+            "max" => Ok(Aggregate::Max),
+            _ => Err(ParseAggregateError),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SymbolClass {
+    Any,
+    Only(Vec<char>),
+}
+
+impl SymbolClass {
+    fn contains(&self, ch: char) -> bool {
+        match self {
+            SymbolClass::Any => true,
+            SymbolClass::Only(symbols) => symbols.contains(&ch),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct GearRule {
+    symbols: SymbolClass,
+    arity: Arity,
+    aggregate: Aggregate,
+}
+
+impl Default for GearRule {
+    fn default() -> Self {
+        Self {
+            symbols: SymbolClass::Only(vec!['*']),
+            arity: Arity::Exactly(2),
+            aggregate: Aggregate::Product,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Adjacency {
+    Moore,
+    VonNeumann,
+    Stencil(Vec<(i32, i32)>),
+}
+
+impl Adjacency {
+    fn offsets(&self) -> Vec<(i32, i32)> {
+        match self {
+            Adjacency::Moore => itertools::iproduct!(-1..=1, -1..=1)
+                .filter(|offset| *offset != (0, 0))
+                .collect(),
+            Adjacency::VonNeumann => vec![(-1, 0), (0, -1), (0, 1), (1, 0)],
+            Adjacency::Stencil(offsets) => offsets.clone(),
+        }
+    }
+
+    fn reach(&self) -> usize {
+        self.offsets()
+            .iter()
+            .map(|(i, _)| i.unsigned_abs() as usize)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+#[derive(Debug)]
+struct ParseAdjacencyError;
+
+impl FromStr for Adjacency {
+    type Err = ParseAdjacencyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "moore" => Ok(Adjacency::Moore),
+            "von-neumann" => Ok(Adjacency::VonNeumann),
+            _ => s
+                .split(';')
+                .map(|offset| {
+                    let (i, j) = offset.split_once(',').ok_or(ParseAdjacencyError)?;
+                    let i = i.trim().parse::<i32>().map_err(|_| ParseAdjacencyError)?;
+                    let j = j.trim().parse::<i32>().map_err(|_| ParseAdjacencyError)?;
+                    Ok((i, j))
+                })
+                .collect::<Result<Vec<(i32, i32)>, ParseAdjacencyError>>()
+                .map(Adjacency::Stencil),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Rules {
+    part_symbols: SymbolClass,
+    gear: GearRule,
+    adjacency: Adjacency,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            part_symbols: SymbolClass::Any,
+            gear: GearRule::default(),
+            adjacency: Adjacency::Moore,
+        }
+    }
+}
+
+fn build_frame(
+    pos: (usize, usize),
+    adjacency: &Adjacency,
+    dims: (usize, usize),
+) -> Vec<(usize, usize)> {
     let (row, col) = (pos.0 as i32, pos.1 as i32);
-    let range = 0..140;
+    let (rows, cols) = (0..dims.0 as i32, 0..dims.1 as i32);
 
-    itertools::iproduct!(row - 1..row + 2, col - 1..col + width as i32 + 1)
+    adjacency
+        .offsets()
+        .into_iter()
+        .map(|(i, j)| (row + i, col + j))
         .filter_map(|(i, j)| {
-            if range.contains(&i) && range.contains(&j) {
+            if rows.contains(&i) && cols.contains(&j) {
                 Some((i as usize, j as usize))
             } else {
                 None
@@ -25,24 +197,40 @@ fn build_frame(pos: (usize, usize), width: usize) -> Vec<(usize, usize)> {
         .collect()
 }
 
-fn validate_token(tokens: &HashMap<(usize, usize), Token>, pos: (usize, usize)) -> Option<Token> {
+fn adjacent_numbers(
+    tokens: &HashMap<(usize, usize), Token>,
+    pos: (usize, usize),
+    dims: (usize, usize),
+    adjacency: &Adjacency,
+) -> Vec<((usize, usize), usize)> {
+    build_frame(pos, adjacency, dims)
+        .iter()
+        .filter_map(|fpos| {
+            if let Some(Token::Number(pos, val)) = tokens.get(fpos) {
+                Some((*pos, *val))
+            } else {
+                None
+            }
+        })
+        .unique_by(|data| data.0)
+        .collect()
+}
+
+fn validate_token(
+    tokens: &HashMap<(usize, usize), Token>,
+    pos: (usize, usize),
+    dims: (usize, usize),
+    rules: &Rules,
+) -> Option<Token> {
+    let rule = &rules.gear;
     match tokens.get(&pos) {
-        Some(Token::Gear(None)) => {
-            let frame = build_frame(pos, 1);
-            let vals = frame
-                .iter()
-                .filter_map(|fpos| {
-                    if let Some(Token::Number(pos, val)) = tokens.get(&fpos) {
-                        Some((pos, val))
-                    } else {
-                        None
-                    }
-                })
-                .unique_by(|data| data.0)
-                .map(|data| data.1.clone())
+        Some(Token::Symbol(ch)) if rule.symbols.contains(*ch) => {
+            let vals = adjacent_numbers(tokens, pos, dims, &rules.adjacency)
+                .into_iter()
+                .map(|data| data.1)
                 .collect::<Vec<usize>>();
-            if vals.len() == 2 {
-                Some(Token::Gear(Some(vals.into_iter().product())))
+            if rule.arity.accepts(vals.len()) {
+                Some(Token::Gear(rule.aggregate.apply(&vals)))
             } else {
                 None
             }
@@ -51,13 +239,208 @@ fn validate_token(tokens: &HashMap<(usize, usize), Token>, pos: (usize, usize))
     }
 }
 
-fn parse_line(row: usize, line: &str) -> Vec<((usize, usize), Token)> {
-    let mut it = line.char_indices();
+fn part_numbers(
+    tokens: &HashMap<(usize, usize), Token>,
+    dims: (usize, usize),
+    rules: &Rules,
+) -> Vec<((usize, usize), usize)> {
+    tokens
+        .iter()
+        .filter(|(_, token)| matches!(token, Token::Symbol(ch) if rules.part_symbols.contains(*ch)))
+        .flat_map(|(pos, _)| adjacent_numbers(tokens, *pos, dims, &rules.adjacency))
+        .unique_by(|data| data.0)
+        .collect()
+}
 
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Number((usize, usize), usize),
+    Symbol((usize, usize), char),
+}
+
+#[derive(Debug, Default)]
+struct Graph {
+    nodes: Vec<Node>,
+    edges: Vec<(usize, usize)>,
+}
+
+impl Graph {
+    fn build(tokens: &HashMap<(usize, usize), Token>, dims: (usize, usize), rules: &Rules) -> Self {
+        let mut graph = Graph::default();
+
+        let numbers = tokens
+            .values()
+            .filter_map(|token| match token {
+                Token::Number(pos, val) => Some((*pos, *val)),
+                _ => None,
+            })
+            .unique_by(|data| data.0)
+            .sorted();
+        let symbols = tokens
+            .iter()
+            .filter_map(|(pos, token)| match token {
+                Token::Symbol(ch) => Some((*pos, *ch)),
+                _ => None,
+            })
+            .sorted();
+
+        let mut index = HashMap::new();
+        for (pos, val) in numbers {
+            index.insert(pos, graph.nodes.len());
+            graph.nodes.push(Node::Number(pos, val));
+        }
+        for (pos, ch) in symbols {
+            let symbol = graph.nodes.len();
+            graph.nodes.push(Node::Symbol(pos, ch));
+            for (npos, _) in adjacent_numbers(tokens, pos, dims, &rules.adjacency) {
+                graph.edges.push((symbol, index[&npos]));
+            }
+        }
+
+        graph
+    }
+
+    fn components(&self) -> Vec<Vec<usize>> {
+        let mut neighbours = vec![Vec::new(); self.nodes.len()];
+        for (a, b) in &self.edges {
+            neighbours[*a].push(*b);
+            neighbours[*b].push(*a);
+        }
+
+        let mut seen = vec![false; self.nodes.len()];
+        let mut components = Vec::new();
+        for start in 0..self.nodes.len() {
+            if seen[start] {
+                continue;
+            }
+            seen[start] = true;
+            let mut component = Vec::new();
+            let mut stack = vec![start];
+            while let Some(node) = stack.pop() {
+                component.push(node);
+                for next in &neighbours[node] {
+                    if !seen[*next] {
+                        seen[*next] = true;
+                        stack.push(*next);
+                    }
+                }
+            }
+            component.sort();
+            components.push(component);
+        }
+
+        components
+    }
+
+    fn machines(&self) -> Vec<Vec<usize>> {
+        self.components()
+            .into_iter()
+            .filter(|component| {
+                let nodes = component.iter().map(|node| &self.nodes[*node]);
+                nodes.clone().any(|node| matches!(node, Node::Symbol(..)))
+                    && nodes.clone().any(|node| matches!(node, Node::Number(..)))
+            })
+            .collect()
+    }
+
+    fn to_dot(&self) -> String {
+        let mut dot = String::from("graph schematic {\n");
+        for (i, node) in self.nodes.iter().enumerate() {
+            let (label, shape) = match node {
+                Node::Number(_, val) => (val.to_string(), "box"),
+                Node::Symbol(_, ch) => (ch.to_string(), "circle"),
+            };
+            let label = label.replace('\\', "\\\\").replace('"', "\\\"");
+            dot.push_str(&format!(
+                "  n{} [label=\"{}\" shape={}];\n",
+                i, label, shape
+            ));
+        }
+        for (a, b) in &self.edges {
+            dot.push_str(&format!("  n{} -- n{};\n", a, b));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn to_json(&self) -> String {
+        let nodes = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| match node {
+                Node::Number(pos, val) => format!(
+                    "{{\"id\":{},\"kind\":\"number\",\"row\":{},\"col\":{},\"value\":{}}}",
+                    i, pos.0, pos.1, val
+                ),
+                Node::Symbol(pos, ch) => format!(
+                    "{{\"id\":{},\"kind\":\"symbol\",\"row\":{},\"col\":{},\"symbol\":\"{}\"}}",
+                    i,
+                    pos.0,
+                    pos.1,
+                    json_escape(&ch.to_string())
+                ),
+            })
+            .collect::<Vec<String>>();
+        let edges = self
+            .edges
+            .iter()
+            .map(|(a, b)| format!("[{},{}]", a, b))
+            .collect::<Vec<String>>();
+        let components = self
+            .components()
+            .iter()
+            .map(|component| format!("[{}]", component.iter().join(",")))
+            .collect::<Vec<String>>();
+        format!(
+            "{{\"nodes\":[{}],\"edges\":[{}],\"components\":[{}]}}\n",
+            nodes.join(","),
+            edges.join(","),
+            components.join(",")
+        )
+    }
+}
+
+fn json_escape(s: &str) -> String {
+    s.chars().fold(String::new(), |mut acc, ch| {
+        match ch {
+            '"' => acc.push_str("\\\""),
+            '\\' => acc.push_str("\\\\"),
+            ch if (ch as u32) < 0x20 => acc.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => acc.push(ch),
+        }
+        acc
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GraphFormat {
+    Dot,
+    Json,
+}
+
+#[derive(Debug)]
+struct ParseGraphFormatError;
+
+impl FromStr for GraphFormat {
+    type Err = ParseGraphFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot" => Ok(GraphFormat::Dot),
+            "json" => Ok(GraphFormat::Json),
+            _ => Err(ParseGraphFormatError),
+        }
+    }
+}
+
+fn parse_line(row: usize, line: &str) -> Vec<((usize, usize), Token)> {
     let mut digit = String::new();
     let mut digits = Vec::new();
+    let mut cols = 0;
 
-    while let Some((i, ch)) = it.next() {
+    for (i, ch) in line.chars().enumerate() {
+        cols = i + 1;
         if ch.is_ascii_digit() {
             digit.push(ch);
         } else if let Ok(d) = digit.parse::<usize>() {
@@ -67,44 +450,749 @@ fn parse_line(row: usize, line: &str) -> Vec<((usize, usize), Token)> {
             }
             digit.clear();
         }
-        if ch.eq(&'*') {
-            digits.push(((row, i), Token::Gear(None)));
+        if !ch.is_ascii_digit() && ch != '.' {
+            digits.push(((row, i), Token::Symbol(ch)));
         }
     }
 
     if let Ok(d) = digit.parse::<usize>() {
         for j in 1..=digit.len() {
-            let pos = (row, 140 - digit.len());
-            digits.push(((row, 140 - j), Token::Number(pos, d)));
+            let pos = (row, cols - digit.len());
+            digits.push(((row, cols - j), Token::Number(pos, d)));
         }
     }
 
     digits
 }
 
+fn read_schematic(reader: impl BufRead) -> io::Result<(Vec<String>, (usize, usize))> {
+    let lines = reader.lines().collect::<io::Result<Vec<String>>>()?;
+
+    let cols = lines.first().map_or(0, |line| line.chars().count());
+
+    if let Some((row, len)) = lines
+        .iter()
+        .map(|line| line.chars().count())
+        .enumerate()
+        .find(|(_, len)| *len != cols)
+    {
+        return Err(ragged_line(row, len, cols));
+    }
+
+    let dims = (lines.len(), cols);
+
+    Ok((lines, dims))
+}
+
+fn ragged_line(row: usize, len: usize, cols: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("line {} has {} columns, expected {}", row + 1, len, cols),
+    )
+}
+
+#[derive(Debug, Default)]
+struct Window {
+    tokens: HashMap<(usize, usize), Token>,
+    rows: VecDeque<(usize, Vec<(usize, usize)>)>,
+    cols: usize,
+}
+
+impl Window {
+    fn push(&mut self, row: usize, tokens: Vec<((usize, usize), Token)>) {
+        self.rows
+            .push_back((row, tokens.iter().map(|(pos, _)| *pos).collect()));
+        self.tokens.extend(tokens);
+    }
+
+    fn pop(&mut self) {
+        if let Some((_, keys)) = self.rows.pop_front() {
+            for key in keys {
+                self.tokens.remove(&key);
+            }
+        }
+    }
+
+    fn evaluate(&self, row: usize, reach: usize, rules: &Rules) -> (usize, usize) {
+        let dims = (row + reach + 1, self.cols);
+
+        let keys = |r: usize| {
+            self.rows
+                .iter()
+                .filter(move |(other, _)| *other == r)
+                .flat_map(|(_, keys)| keys.iter().copied())
+        };
+
+        let parts = self
+            .rows
+            .iter()
+            .flat_map(|(_, keys)| keys.iter().copied())
+            .filter(|pos| {
+                matches!(self.tokens.get(pos), Some(Token::Symbol(ch)) if rules.part_symbols.contains(*ch))
+            })
+            .flat_map(|pos| adjacent_numbers(&self.tokens, pos, dims, &rules.adjacency))
+            .filter(|data| data.0 .0 == row)
+            .unique_by(|data| data.0)
+            .map(|data| data.1)
+            .sum();
+
+        let ratios = keys(row)
+            .filter_map(|pos| validate_token(&self.tokens, pos, dims, rules))
+            .fold(0, |acc, t| match t {
+                Token::Gear(val) => acc + val,
+                _ => acc,
+            });
+
+        (parts, ratios)
+    }
+}
+
+fn stream_schematic(reader: impl BufRead, rules: &Rules) -> io::Result<(usize, usize)> {
+    let reach = rules.adjacency.reach();
+    let mut window = Window::default();
+    let mut totals = (0, 0);
+    let mut rows = 0;
+
+    for (row, line) in reader.lines().enumerate() {
+        let line = line?;
+        if row == 0 {
+            window.cols = line.chars().count();
+        } else if line.chars().count() != window.cols {
+            return Err(ragged_line(row, line.chars().count(), window.cols));
+        }
+
+        window.push(row, parse_line(row, &line));
+        rows = row + 1;
+
+        if row >= reach {
+            let (parts, ratios) = window.evaluate(row - reach, reach, rules);
+            totals = (totals.0 + parts, totals.1 + ratios);
+        }
+        if window.rows.len() > 2 * reach {
+            window.pop();
+        }
+    }
+
+    for row in rows.saturating_sub(reach)..rows {
+        let (parts, ratios) = window.evaluate(row, reach, rules);
+        totals = (totals.0 + parts, totals.1 + ratios);
+    }
+
+    Ok(totals)
+}
+
+#[derive(Debug)]
+struct Schematic {
+    lines: Vec<String>,
+    dims: (usize, usize),
+    rules: Rules,
+    tokens: HashMap<(usize, usize), Token>,
+    parts: HashMap<(usize, usize), usize>,
+    gears: HashMap<(usize, usize), usize>,
+    totals: (usize, usize),
+}
+
+impl Schematic {
+    fn new(lines: Vec<String>, dims: (usize, usize), rules: Rules) -> Self {
+        let tokens = lines
+            .iter()
+            .enumerate()
+            .fold(HashMap::new(), |acc, (row, line)| {
+                let mut acc = acc;
+                acc.extend(parse_line(row, &line[..]));
+                acc
+            });
+
+        let parts = part_numbers(&tokens, dims, &rules)
+            .into_iter()
+            .collect::<HashMap<(usize, usize), usize>>();
+
+        let gears = tokens
+            .keys()
+            .filter_map(|pos| match validate_token(&tokens, *pos, dims, &rules) {
+                Some(Token::Gear(val)) => Some((*pos, val)),
+                _ => None,
+            })
+            .collect::<HashMap<(usize, usize), usize>>();
+
+        let totals = (parts.values().sum(), gears.values().sum());
+
+        Self {
+            lines,
+            dims,
+            rules,
+            tokens,
+            parts,
+            gears,
+            totals,
+        }
+    }
+
+    fn symbols_near(&self, cells: &[(usize, usize)]) -> Vec<(usize, usize)> {
+        let offsets = self.rules.adjacency.offsets();
+        cells
+            .iter()
+            .flat_map(|cell| {
+                offsets
+                    .iter()
+                    .map(move |(i, j)| (cell.0 as i32 - i, cell.1 as i32 - j))
+                    .chain([(cell.0 as i32, cell.1 as i32)])
+            })
+            .filter(|(i, j)| {
+                (0..self.dims.0 as i32).contains(i) && (0..self.dims.1 as i32).contains(j)
+            })
+            .map(|(i, j)| (i as usize, j as usize))
+            .filter(|pos| matches!(self.tokens.get(pos), Some(Token::Symbol(_))))
+            .unique()
+            .collect()
+    }
+
+    fn numbers_near(
+        &self,
+        cells: &[(usize, usize)],
+        symbols: &[(usize, usize)],
+    ) -> Vec<(usize, usize)> {
+        let inside = cells.iter().filter_map(|cell| match self.tokens.get(cell) {
+            Some(Token::Number(start, _)) => Some(*start),
+            _ => None,
+        });
+        let adjacent = symbols
+            .iter()
+            .flat_map(|pos| adjacent_numbers(&self.tokens, *pos, self.dims, &self.rules.adjacency))
+            .map(|data| data.0);
+        inside.chain(adjacent).unique().collect()
+    }
+
+    fn is_part(&self, start: (usize, usize)) -> bool {
+        let cells = (start.1..self.dims.1)
+            .map(|col| (start.0, col))
+            .take_while(|pos| matches!(self.tokens.get(pos), Some(Token::Number(other, _)) if *other == start))
+            .collect::<Vec<(usize, usize)>>();
+        self.symbols_near(&cells).iter().any(|pos| {
+            matches!(self.tokens.get(pos), Some(Token::Symbol(ch)) if self.rules.part_symbols.contains(*ch))
+        })
+    }
+
+    fn set(&mut self, pos: (usize, usize), ch: char) -> io::Result<(usize, usize)> {
+        let (row, col) = pos;
+        if row >= self.dims.0 || col >= self.dims.1 {
+            return Err(invalid_input(&format!(
+                "cell {},{} is out of bounds",
+                row, col
+            )));
+        }
+        let mut chars = self.lines[row].chars().collect::<Vec<char>>();
+        chars[col] = ch;
+
+        let digit = |c: usize| chars[c].is_ascii_digit();
+        let lo = (0..col)
+            .rev()
+            .take_while(|c| digit(*c))
+            .last()
+            .unwrap_or(col);
+        let hi = (col + 1..self.dims.1)
+            .take_while(|c| digit(*c))
+            .last()
+            .unwrap_or(col);
+        let cells = (lo..=hi).map(|c| (row, c)).collect::<Vec<(usize, usize)>>();
+
+        let symbols_before = self.symbols_near(&cells);
+        let numbers_before = self.numbers_near(&cells, &symbols_before);
+
+        for pos in &symbols_before {
+            if let Some(val) = self.gears.remove(pos) {
+                self.totals.1 -= val;
+            }
+        }
+        for pos in &numbers_before {
+            if let Some(val) = self.parts.remove(pos) {
+                self.totals.0 -= val;
+            }
+        }
+        for pos in &cells {
+            self.tokens.remove(pos);
+        }
+
+        self.lines[row] = chars.iter().collect();
+        let tokens = parse_line(row, &chars[lo..=hi].iter().collect::<String>())
+            .into_iter()
+            .map(|((r, c), token)| match token {
+                Token::Number((nr, nc), val) => ((r, c + lo), Token::Number((nr, nc + lo), val)),
+                token => ((r, c + lo), token),
+            });
+        self.tokens.extend(tokens);
+
+        let symbols_after = self.symbols_near(&cells);
+        let numbers_after = self.numbers_near(&cells, &symbols_after);
+
+        for pos in symbols_before.into_iter().chain(symbols_after).unique() {
+            if let Some(Token::Gear(val)) =
+                validate_token(&self.tokens, pos, self.dims, &self.rules)
+            {
+                let old = self.gears.insert(pos, val).unwrap_or(0);
+                self.totals.1 = self.totals.1 + val - old;
+            }
+        }
+        for pos in numbers_before.into_iter().chain(numbers_after).unique() {
+            if let Some(Token::Number(start, val)) = self.tokens.get(&pos) {
+                if *start == pos && self.is_part(pos) {
+                    let old = self.parts.insert(pos, *val).unwrap_or(0);
+                    self.totals.0 = self.totals.0 + val - old;
+                }
+            }
+        }
+
+        Ok(self.totals)
+    }
+
+    fn clear(&mut self, pos: (usize, usize)) -> io::Result<(usize, usize)> {
+        self.set(pos, '.')
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Highlight {
+    Plain,
+    Part,
+    Number,
+    Gear,
+    RejectedGear,
+}
+
+impl Highlight {
+    fn ansi(&self) -> &str {
+        match self {
+            Highlight::Plain => "",
+            Highlight::Part => "\x1b[32m",
+            Highlight::Number => "\x1b[31m",
+            Highlight::Gear => "\x1b[1;33m",
+            Highlight::RejectedGear => "\x1b[35m",
+        }
+    }
+
+    fn class(&self) -> &str {
+        match self {
+            Highlight::Plain => "plain",
+            Highlight::Part => "part",
+            Highlight::Number => "number",
+            Highlight::Gear => "gear",
+            Highlight::RejectedGear => "rejected",
+        }
+    }
+}
+
+impl Schematic {
+    fn highlight(&self, pos: (usize, usize)) -> Highlight {
+        match self.tokens.get(&pos) {
+            Some(Token::Number(start, _)) if self.parts.contains_key(start) => Highlight::Part,
+            Some(Token::Number(..)) => Highlight::Number,
+            Some(Token::Symbol(_)) if self.gears.contains_key(&pos) => Highlight::Gear,
+            Some(Token::Symbol(ch)) if self.rules.gear.symbols.contains(*ch) => {
+                Highlight::RejectedGear
+            }
+            _ => Highlight::Plain,
+        }
+    }
+
+    fn runs(&self, row: usize) -> Vec<(Highlight, &str)> {
+        let line = &self.lines[row];
+        let mut runs: Vec<(Highlight, usize, usize)> = Vec::new();
+        for (col, (byte, ch)) in line.char_indices().enumerate() {
+            let highlight = self.highlight((row, col));
+            match runs.last_mut() {
+                Some((last, _, end)) if *last == highlight => *end = byte + ch.len_utf8(),
+                _ => runs.push((highlight, byte, byte + ch.len_utf8())),
+            }
+        }
+        runs.into_iter()
+            .map(|(highlight, start, end)| (highlight, &line[start..end]))
+            .collect()
+    }
+
+    fn render_ansi(&self) -> String {
+        (0..self.dims.0)
+            .map(|row| {
+                self.runs(row)
+                    .into_iter()
+                    .map(|(highlight, text)| match highlight {
+                        Highlight::Plain => text.to_string(),
+                        _ => format!("{}{}\x1b[0m", highlight.ansi(), text),
+                    })
+                    .collect::<String>()
+                    + "\n"
+            })
+            .collect()
+    }
+
+    fn render_html(&self) -> String {
+        let body = (0..self.dims.0)
+            .map(|row| {
+                self.runs(row)
+                    .into_iter()
+                    .map(|(highlight, text)| {
+                        let text = text
+                            .replace('&', "&amp;")
+                            .replace('<', "&lt;")
+                            .replace('>', "&gt;");
+                        match highlight {
+                            Highlight::Plain => text,
+                            _ => format!("<span class=\"{}\">{}</span>", highlight.class(), text),
+                        }
+                    })
+                    .collect::<String>()
+                    + "\n"
+            })
+            .collect::<String>();
+        format!(
+            "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>Schematic</title>
+<style>
+body {{ background: #0f0f23; color: #666; }}
+pre {{ font-family: monospace; line-height: 1.2; }}
+.part {{ color: #2ecc40; }}
+.number {{ color: #ff4136; }}
+.gear {{ color: #ffdc00; font-weight: bold; }}
+.rejected {{ color: #b10dc9; }}
+</style>
+</head>
+<body>
+<p>Part numbers: {} &middot; Gear ratios: {}</p>
+<pre>
+{}</pre>
+</body>
+</html>
+",
+            self.totals.0, self.totals.1, body
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RenderFormat {
+    Ansi,
+    Html,
+}
+
+#[derive(Debug)]
+struct ParseRenderFormatError;
+
+impl FromStr for RenderFormat {
+    type Err = ParseRenderFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ansi" => Ok(RenderFormat::Ansi),
+            "html" => Ok(RenderFormat::Html),
+            _ => Err(ParseRenderFormatError),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Options {
+    rules: Rules,
+    stream: bool,
+    graph: Option<GraphFormat>,
+    graph_output: Option<String>,
+    components: bool,
+    edits: Vec<((usize, usize), char)>,
+    render: Option<RenderFormat>,
+    render_output: Option<String>,
+}
+
+impl Options {
+    fn from_args() -> io::Result<Self> {
+        let mut options = Self {
+            rules: Rules::default(),
+            stream: false,
+            graph: None,
+            graph_output: None,
+            components: false,
+            edits: Vec::new(),
+            render: None,
+            render_output: None,
+        };
+
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or(invalid_input(&format!("{} needs a value", arg)))
+            };
+            match &arg[..] {
+                "--stream" => options.stream = true,
+                "--components" => options.components = true,
+                "--graph" => {
+                    let format = value()?;
+                    options.graph = Some(format.parse().map_err(|_| {
+                        invalid_input(&format!("unknown graph format `{}`", format))
+                    })?)
+                }
+                "--graph-output" => options.graph_output = Some(value()?),
+                "--render" => {
+                    let format = value()?;
+                    options.render = Some(format.parse().map_err(|_| {
+                        invalid_input(&format!("unknown render format `{}`", format))
+                    })?)
+                }
+                "--render-output" => options.render_output = Some(value()?),
+                "--edit" | "--clear" => {
+                    let edit = value()?;
+                    let mut it = edit.splitn(3, ',');
+                    let row = it.next().and_then(|row| row.trim().parse().ok());
+                    let col = it.next().and_then(|col| col.trim().parse().ok());
+                    let ch = match (&arg[..], it.next()) {
+                        ("--clear", None) => Some('.'),
+                        ("--edit", Some(ch)) if ch.chars().count() == 1 => ch.chars().next(),
+                        _ => None,
+                    };
+                    match (row, col, ch) {
+                        (Some(row), Some(col), Some(ch)) => options.edits.push(((row, col), ch)),
+                        _ => return Err(invalid_input(&format!("invalid cell edit `{}`", edit))),
+                    }
+                }
+                "--part-symbols" => {
+                    options.rules.part_symbols = SymbolClass::Only(value()?.chars().collect())
+                }
+                "--gear-symbols" => {
+                    options.rules.gear.symbols = SymbolClass::Only(value()?.chars().collect())
+                }
+                "--adjacency" => {
+                    let adjacency = value()?;
+                    options.rules.adjacency = adjacency
+                        .parse()
+                        .map_err(|_| invalid_input(&format!("invalid adjacency `{}`", adjacency)))?
+                }
+                "--teeth" => {
+                    let teeth = value()?;
+                    options.rules.gear.arity = teeth
+                        .parse()
+                        .map_err(|_| invalid_input(&format!("invalid tooth count `{}`", teeth)))?
+                }
+                "--aggregate" => {
+                    let aggregate = value()?;
+                    options.rules.gear.aggregate = aggregate
+                        .parse()
+                        .map_err(|_| invalid_input(&format!("unknown aggregate `{}`", aggregate)))?
+                }
+                _ => return Err(invalid_input(&format!("unknown argument `{}`", arg))),
+            }
+        }
+
+        if options.stream
+            && (options.graph.is_some()
+                || options.components
+                || !options.edits.is_empty()
+                || options.render.is_some())
+        {
+            return Err(invalid_input(
+                "--graph, --components, --edit and --render cannot be combined with --stream",
+            ));
+        }
+
+        Ok(options)
+    }
+}
+
+fn invalid_input(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+fn report_graph(graph: &Graph, options: &Options) -> io::Result<()> {
+    if let Some(format) = options.graph {
+        let (contents, default) = match format {
+            GraphFormat::Dot => (graph.to_dot(), "schematic.dot"),
+            GraphFormat::Json => (graph.to_json(), "schematic.json"),
+        };
+        let path = options.graph_output.as_deref().unwrap_or(default);
+        fs::write(path, contents)?;
+        println!("Graph: {}", path);
+    }
+
+    if options.components {
+        let machines = graph.machines();
+        println!("Machines: {}", machines.len());
+        for (i, machine) in machines.iter().enumerate() {
+            let (symbols, parts): (Vec<&Node>, Vec<&Node>) = machine
+                .iter()
+                .map(|node| &graph.nodes[*node])
+                .partition(|node| matches!(node, Node::Symbol(..)));
+            println!(
+                "  machine {}: symbols {} <- parts {}",
+                i + 1,
+                symbols
+                    .iter()
+                    .map(|node| match node {
+                        Node::Symbol(pos, ch) => format!("{}@{},{}", ch, pos.0, pos.1),
+                        Node::Number(..) => unreachable!(),
+                    })
+                    .join(" "),
+                parts
+                    .iter()
+                    .map(|node| match node {
+                        Node::Number(_, val) => val.to_string(),
+                        Node::Symbol(..) => unreachable!(),
+                    })
+                    .join(" ")
+            );
+        }
+    }
+
+    Ok(())
+}
+
 fn main() -> io::Result<()> {
+    let options = Options::from_args()?;
+
     let file = File::open("input.txt")?;
     let reader = BufReader::new(file);
 
-    let schematic = reader
-        .lines()
-        .filter_map(|line| line.ok())
-        .enumerate()
-        .fold(HashMap::new(), |acc, (row, line)| {
-            let mut acc = acc;
-            acc.extend(parse_line(row, &line[..]));
-            acc
-        });
+    if options.stream {
+        let (parts, ratios) = stream_schematic(reader, &options.rules)?;
+        println!("Part numbers: {}", parts);
+        println!("Gear ratios: {}", ratios);
+        return Ok(());
+    }
 
-    let sum = schematic
-        .iter()
-        .filter_map(|(pos, _)| validate_token(&schematic, pos.clone()))
-        .fold(0, |acc, t| match t {
-            Token::Gear(Some(val)) => acc + val,
-            _ => acc,
-        });
+    let (lines, dims) = read_schematic(reader)?;
+
+    let mut schematic = Schematic::new(lines, dims, options.rules.clone());
+
+    let (parts, ratios) = schematic.totals;
+
+    println!("Part numbers: {}", parts);
+    println!("Gear ratios: {}", ratios);
+
+    if options.graph.is_some() || options.components {
+        let graph = Graph::build(&schematic.tokens, dims, &options.rules);
+        report_graph(&graph, &options)?;
+    }
+
+    for (pos, ch) in &options.edits {
+        let (parts, ratios) = match ch {
+            '.' => schematic.clear(*pos)?,
+            _ => schematic.set(*pos, *ch)?,
+        };
+        println!(
+            "Edit {},{} -> `{}`: part numbers {}, gear ratios {}",
+            pos.0, pos.1, ch, parts, ratios
+        );
+    }
 
-    println!("{}", sum);
+    match options.render {
+        Some(RenderFormat::Ansi) => print!("{}", schematic.render_ansi()),
+        Some(RenderFormat::Html) => {
+            let path = options.render_output.as_deref().unwrap_or("schematic.html");
+            fs::write(path, schematic.render_html())?;
+            println!("Render: {}", path);
+        }
+        None => {}
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "467..114..\n...*......\n..35..633.\n......#...\n617*......\n\
+                           .....+.58.\n..592.....\n......755.\n...$.*....\n.664.598..\n";
+
+    const CELLS: &[char] = &[
+        '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', '.', '.', '.', '.', '.', '.', '*', '#',
+        '$', '§',
+    ];
+
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((self.0 >> 33) % n as u64) as usize
+        }
+    }
+
+    fn random_grid(rng: &mut Lcg, rows: usize, cols: usize) -> String {
+        (0..rows)
+            .map(|_| {
+                (0..cols)
+                    .map(|_| CELLS[rng.below(CELLS.len())])
+                    .collect::<String>()
+                    + "\n"
+            })
+            .collect()
+    }
+
+    fn rule_sets() -> Vec<Rules> {
+        vec![
+            Rules::default(),
+            Rules {
+                gear: GearRule {
+                    symbols: SymbolClass::Any,
+                    arity: Arity::AtLeast(1),
+                    aggregate: Aggregate::Sum,
+                },
+                adjacency: Adjacency::VonNeumann,
+                ..Rules::default()
+            },
+            Rules {
+                part_symbols: SymbolClass::Only(vec!['#', '§']),
+                gear: GearRule {
+                    symbols: SymbolClass::Only(vec!['*', '$']),
+                    arity: Arity::AtLeast(2),
+                    aggregate: Aggregate::Max,
+                },
+                adjacency: Adjacency::Stencil(vec![(-2, 0), (0, 1), (2, -1), (1, 1)]),
+            },
+        ]
+    }
+
+    fn full_totals(grid: &str, rules: &Rules) -> (usize, usize) {
+        let (lines, dims) = read_schematic(grid.as_bytes()).unwrap();
+        Schematic::new(lines, dims, rules.clone()).totals
+    }
+
+    #[test]
+    fn streaming_matches_full() {
+        assert_eq!(full_totals(EXAMPLE, &Rules::default()), (4361, 467835));
+
+        let mut rng = Lcg(17);
+        let grids = (0..20)
+            .map(|_| random_grid(&mut rng, 12, 15))
+            .chain([EXAMPLE.to_string()])
+            .collect::<Vec<String>>();
+
+        for rules in rule_sets() {
+            for grid in &grids {
+                let streamed = stream_schematic(grid.as_bytes(), &rules).unwrap();
+                assert_eq!(streamed, full_totals(grid, &rules));
+            }
+        }
+    }
+    #[test]
+    fn incremental_matches_recompute() {
+        let mut rng = Lcg(29);
+        for rules in rule_sets() {
+            let grid = random_grid(&mut rng, 10, 12);
+            let (lines, dims) = read_schematic(grid.as_bytes()).unwrap();
+            let mut schematic = Schematic::new(lines, dims, rules.clone());
+
+            for _ in 0..300 {
+                let pos = (rng.below(dims.0), rng.below(dims.1));
+                let totals = match CELLS[rng.below(CELLS.len())] {
+                    '.' => schematic.clear(pos).unwrap(),
+                    ch => schematic.set(pos, ch).unwrap(),
+                };
+                let recomputed = Schematic::new(schematic.lines.clone(), dims, rules.clone());
+                assert_eq!(totals, recomputed.totals);
+            }
+        }
+    }
+}
@@ -1,46 +1,196 @@
 use std::collections::HashSet;
+use std::env;
 use std::fs::File;
 use std::io::{self, prelude::*, BufReader};
+use std::str::FromStr;
 
 #[derive(Debug)]
 struct Card {
+    id: usize,
     winners: HashSet<usize>,
     numbers: HashSet<usize>,
 }
 
 impl Card {
-    pub fn new(winners: HashSet<usize>, numbers: HashSet<usize>) -> Self {
-        Self { winners, numbers }
+    pub fn new(id: usize, winners: HashSet<usize>, numbers: HashSet<usize>) -> Self {
+        Self {
+            id,
+            winners,
+            numbers,
+        }
     }
 }
 
 #[derive(Debug)]
 struct CardResult {
+    id: usize,
     matches: usize,
-    cardinality: usize,
+    points: Option<u128>,
+    cardinality: u128,
 }
 
 impl CardResult {
-    pub fn new(matches: usize, cardinality: usize) -> Self {
+    pub fn new(id: usize, matches: usize, cardinality: u128) -> Self {
         Self {
+            id,
             matches,
+            points: match matches {
+                0 => Some(0),
+                n => 1u128.checked_shl(n as u32 - 1),
+            },
             cardinality,
         }
     }
 }
 
+fn overflow(what: &str, card: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{} overflow at card {}", what, card),
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Overrun {
+    Clamp,
+    Wrap,
+    Error,
+}
+
+#[derive(Debug)]
+struct ParseOverrunError;
+
+impl FromStr for Overrun {
+    type Err = ParseOverrunError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clamp" => Ok(Overrun::Clamp),
+            "wrap" => Ok(Overrun::Wrap),
+            "error" => Ok(Overrun::Error),
+            _ => Err(ParseOverrunError),
+        }
+    }
+}
+
+fn sort_deck(results: &mut [CardResult]) -> io::Result<()> {
+    results.sort_by_key(|res| res.id);
+    match results.windows(2).find(|pair| pair[0].id == pair[1].id) {
+        Some(pair) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("duplicate card {}", pair[0].id),
+        )),
+        None => Ok(()),
+    }
+}
+
+fn overruns(results: &[CardResult]) -> Vec<(usize, usize, usize)> {
+    let last = results.last().map_or(0, |res| res.id);
+    results
+        .iter()
+        .map(|res| (res.id, res.matches, last - res.id))
+        .filter(|(_, matches, following)| matches > following)
+        .collect()
+}
+
+fn add(lhs: u128, rhs: u128, card: usize) -> io::Result<u128> {
+    lhs.checked_add(rhs).ok_or(overflow("copy count", card))
+}
+
+fn sub(lhs: u128, rhs: u128, card: usize) -> io::Result<u128> {
+    lhs.checked_sub(rhs).ok_or(overflow("copy count", card))
+}
+
+// Expects a deck sorted by ID. Copies go to the IDs following a card, skipping
+// IDs missing from the deck. Wrapped copies continue from the lowest ID and are
+// counted, but do not win further copies, since those cards have been scored.
+fn propagate(results: &mut [CardResult], policy: Overrun) -> io::Result<()> {
+    let n = results.len();
+    let (first, last) = match (results.first(), results.last()) {
+        (Some(first), Some(last)) => (first.id, last.id),
+        _ => return Ok(()),
+    };
+    let span = last - first + 1;
+    let below = |results: &[CardResult], id: usize| results.partition_point(|res| res.id < id);
+
+    let mut starts = vec![0u128; n + 1];
+    let mut ends = vec![0u128; n + 1];
+    let mut wrap_start = 0u128;
+    let mut wrap_ends = vec![0u128; n + 1];
+    let mut laps = 0u128;
+    let mut running = 0u128;
+
+    for i in 0..n {
+        let id = results[i].id;
+        running = add(sub(running, ends[i], id)?, starts[i], id)?;
+
+        let cardinality = add(results[i].cardinality, running, id)?;
+        results[i].cardinality = cardinality;
+
+        let matches = results[i].matches;
+        if matches == 0 {
+            continue;
+        }
+
+        let end = below(results, id.saturating_add(matches).saturating_add(1));
+        if end > i + 1 {
+            starts[i + 1] = add(starts[i + 1], cardinality, id)?;
+            ends[end] = add(ends[end], cardinality, id)?;
+        }
+
+        if matches > last - id {
+            match policy {
+                Overrun::Clamp => {}
+                Overrun::Wrap => {
+                    let wrapped = matches - (last - id);
+                    let full = (wrapped / span) as u128;
+                    let lap = full
+                        .checked_mul(cardinality)
+                        .ok_or(overflow("copy count", id))?;
+                    laps = add(laps, lap, id)?;
+                    let end = below(results, first + wrapped % span);
+                    if end > 0 {
+                        wrap_start = add(wrap_start, cardinality, id)?;
+                        wrap_ends[end] = add(wrap_ends[end], cardinality, id)?;
+                    }
+                }
+                Overrun::Error => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("card {} wins copies past the end of the deck", id),
+                    ))
+                }
+            }
+        }
+    }
+
+    if policy == Overrun::Wrap {
+        let mut running = wrap_start;
+        for (i, res) in results.iter_mut().enumerate() {
+            running = sub(running, wrap_ends[i], res.id)?;
+            res.cardinality = add(add(res.cardinality, running, res.id)?, laps, res.id)?;
+        }
+    }
+
+    Ok(())
+}
+
 fn parse_line(line: &str) -> Card {
     let start = line.find(':').unwrap();
-    parse_card(&line[start + 1..])
+    let id = line[..start]
+        .trim_start_matches("Card")
+        .trim()
+        .parse::<usize>()
+        .unwrap();
+    parse_card(id, &line[start + 1..])
 }
 
-fn parse_card(card: &str) -> Card {
+fn parse_card(id: usize, card: &str) -> Card {
     let mut it = card.split('|');
     let winners = parse_numbers(it.next().unwrap());
     let numbers = parse_numbers(it.next().unwrap());
-    Card::new(winners, numbers)
+    Card::new(id, winners, numbers)
 }
-
 fn parse_numbers(numbers: &str) -> HashSet<usize> {
     numbers
         .trim()
@@ -50,28 +200,132 @@ fn parse_numbers(numbers: &str) -> HashSet<usize> {
         .collect()
 }
 
+struct Options {
+    overrun: Overrun,
+}
+
+impl Options {
+    fn from_args() -> io::Result<Self> {
+        let mut options = Self {
+            overrun: Overrun::Clamp,
+        };
+
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or(invalid_input(&format!("{} needs a value", arg)))
+            };
+            match &arg[..] {
+                "--overrun" => {
+                    let policy = value()?;
+                    options.overrun = policy.parse::<Overrun>().map_err(|_| {
+                        invalid_input(&format!("unknown overrun policy `{}`", policy))
+                    })?;
+                }
+                _ => return Err(invalid_input(&format!("unknown argument `{}`", arg))),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+fn invalid_input(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
 fn main() -> io::Result<()> {
+    let options = Options::from_args()?;
+
     let file = File::open("input.txt")?;
     let reader = BufReader::new(file);
 
-    let mut results = reader
-        .lines()
-        .filter_map(|line| line.ok())
-        .map(|line| parse_line(&line[..]))
-        .map(|card| CardResult::new(card.winners.intersection(&card.numbers).count(), 1))
-        .collect::<Vec<CardResult>>();
-
-    for i in 0..results.len() {
-        for _ in 0..results[i].cardinality {
-            for j in 1..=results[i].matches {
-                results[i + j].cardinality += 1;
+    let mut results = Vec::new();
+    let mut unreadable = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        match line {
+            Ok(line) => {
+                let card = parse_line(&line[..]);
+                let matches = card.winners.intersection(&card.numbers).count();
+                results.push(CardResult::new(card.id, matches, 1));
             }
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => unreadable.push(i + 1),
+            Err(e) => return Err(e),
         }
     }
 
-    let sum = results.iter().fold(0, |acc, res| acc + res.cardinality);
+    if !unreadable.is_empty() {
+        println!(
+            "Skipped {} unreadable lines: {}",
+            unreadable.len(),
+            unreadable
+                .iter()
+                .map(|row| row.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+    }
+
+    sort_deck(&mut results)?;
+
+    let overrunning = overruns(&results);
+    if !overrunning.is_empty() {
+        println!("Overruns: {}", overrunning.len());
+        for (id, matches, following) in &overrunning {
+            println!(
+                "  card {}: {} matches, {} IDs follow",
+                id, matches, following
+            );
+        }
+    }
+
+    propagate(&mut results, options.overrun)?;
+
+    let points = results.iter().try_fold(0u128, |acc, res| {
+        res.points
+            .and_then(|points| acc.checked_add(points))
+            .ok_or(overflow("point", res.id))
+    })?;
+    let sum = results.iter().try_fold(0u128, |acc, res| {
+        acc.checked_add(res.cardinality)
+            .ok_or(overflow("card count", res.id))
+    })?;
 
-    println!("{}", sum);
+    println!("Points: {}", points);
+    println!("Cards: {}", sum);
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(deck: &[(usize, usize)], policy: Overrun) -> io::Result<u128> {
+        let mut results = deck
+            .iter()
+            .map(|(id, matches)| CardResult::new(*id, *matches, 1))
+            .collect::<Vec<CardResult>>();
+        sort_deck(&mut results)?;
+        propagate(&mut results, policy)?;
+        Ok(results.iter().map(|res| res.cardinality).sum())
+    }
+
+    #[test]
+    fn overrun_policies_on_sparse_deck() {
+        let deck = [(5, 1), (1, 3), (3, 1)];
+        assert_eq!(count(&deck, Overrun::Clamp).unwrap(), 4);
+        assert_eq!(count(&deck, Overrun::Wrap).unwrap(), 5);
+        let err = count(&deck, Overrun::Error).unwrap_err();
+        assert!(err.to_string().contains("card 5"));
+    }
+
+    #[test]
+    fn wrapped_laps_skip_missing_ids() {
+        // Card 3 wins IDs 4..=9, two full laps over the span 1..=3. Cards 1
+        // and 3 get two wrapped copies each; the laps over ID 2 are dropped.
+        let deck = [(1, 0), (3, 6)];
+        assert_eq!(count(&deck, Overrun::Wrap).unwrap(), 6);
+    }
+}